@@ -6,6 +6,40 @@ use std::{
 use bit_vec::BitVec;
 use fxhash::FxHasher;
 
+/// Arbitrary salt used to decorrelate the second base hash from the first
+/// within [`hash_indices`].
+const DOUBLE_HASH_SALT: u64 = 0x9E37_79B9_7F4A_7C15;
+
+/// Get the sequence of `k` bit indices for `key` against a filter of
+/// `n_bits`. This automatically applies the modulo of the number of bits
+/// within the bit vector and is therefore ready to use.
+///
+/// Rather than re-feeding the key into a single accumulating hasher `k`
+/// times, this uses the Kirsch-Mitzenmacher "double hashing" technique: two
+/// independent base hashes `h1` and `h2` are computed once and each of the
+/// `k` indices is derived as `h1 + i * h2`. This gives statistically
+/// independent positions from only two hash computations, see
+/// <https://www.eecs.harvard.edu/~michaelm/postscripts/rsa2008.pdf>.
+///
+/// Shared by both [`BloomFilter`] and [`CountingBloomFilter`] so they agree
+/// on which slots a given key maps to.
+fn hash_indices<K: Hash>(key: &K, n_bits: usize, k: usize) -> Vec<usize> {
+    let mut h1 = FxHasher::default();
+    key.hash(&mut h1);
+    let h1 = h1.finish();
+
+    let mut h2 = FxHasher::default();
+    key.hash(&mut h2);
+    // Salt to decorrelate `h2` from `h1`, rather than starting both
+    // hashers from identical state.
+    DOUBLE_HASH_SALT.hash(&mut h2);
+    let h2 = h2.finish();
+
+    (0..k as u64)
+        .map(|i| (h1.wrapping_add(i.wrapping_mul(h2)) % n_bits as u64) as usize)
+        .collect()
+}
+
 /// Implementation of a Bloom filter.
 ///
 /// This is used to determine whether or not a value is contained within a set.
@@ -32,27 +66,45 @@ impl<K: Hash> BloomFilter<K> {
         }
     }
 
+    /// Create a new [`BloomFilter`] sized for `expected_items` at a target
+    /// `fp_rate` false-positive rate.
+    ///
+    /// This computes the optimal bit count and number of hash rounds using
+    /// the standard formulas:
+    ///
+    /// - `m = ceil(-(n * ln(p)) / (ln 2)^2)`
+    /// - `k = round((m / n) * ln 2)`
+    ///
+    /// `k` is clamped to at least 1.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `expected_items` is `0` or `fp_rate` is not within `(0, 1)`.
+    pub fn with_rate(expected_items: usize, fp_rate: f64) -> Self {
+        assert!(expected_items > 0, "expected_items must be greater than 0");
+        assert!(
+            fp_rate > 0.0 && fp_rate < 1.0,
+            "fp_rate must be within (0, 1), got {fp_rate}"
+        );
+
+        let n = expected_items as f64;
+        let m = (-(n * fp_rate.ln()) / std::f64::consts::LN_2.powi(2)).ceil() as usize;
+        let k = (((m as f64 / n) * std::f64::consts::LN_2).round() as usize).max(1);
+
+        Self::new(m, k)
+    }
+
     /// Insert a value into the bloom filter.
     ///
     /// As this is a bloom filter, the value isn't _actually_ inserted. Only the
     /// hash of the item which was given. An internal bit vector is updated based
     /// on the hash of the contents that was provided.
     pub fn insert(&mut self, key: K) {
-        let mut h = FxHasher::default();
-        for _ in 0..self.k {
-            let index = self.hash_index(&key, &mut h);
+        for index in hash_indices(&key, self.n_bits, self.k) {
             self.inner.set(index, true);
         }
     }
 
-    /// Get the hash index to set the bit as occupied within the internal bit
-    /// vector. This automatically applies the modulo of the number of bits
-    /// within the bit vector and is therefore ready to use.
-    fn hash_index<H: Hasher>(&mut self, key: &K, hasher: &mut H) -> usize {
-        key.hash(hasher);
-        hasher.finish() as usize % self.n_bits
-    }
-
     /// Determine whether a value is contained within the bloom filter.
     ///
     /// # Notes
@@ -65,9 +117,7 @@ impl<K: Hash> BloomFilter<K> {
     /// However, when any of the bits are 0 for an item this means the value is
     /// definitely not within the set and we can return `false` for certain.
     pub fn check(&mut self, key: K) -> bool {
-        let mut h = FxHasher::default();
-        for _ in 0..self.k {
-            let index = self.hash_index(&key, &mut h);
+        for index in hash_indices(&key, self.n_bits, self.k) {
             // Safety: A bound check is not required here as the index is
             // calculated from a modulo operation against the number of bits
             // within the vector
@@ -82,15 +132,237 @@ impl<K: Hash> BloomFilter<K> {
         }
         true
     }
+
+    /// Combine this filter with `other`, returning a new filter whose set
+    /// bits are the union of both. A key that either filter would report as
+    /// present is reported as present in the result.
+    ///
+    /// Useful for merging filters built independently across shards, e.g.
+    /// per-partition filters in a distributed datastore that are combined
+    /// once all partitions have been built.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `self` and `other` don't share the same `n_bits` and `k`.
+    pub fn union(&self, other: &BloomFilter<K>) -> BloomFilter<K> {
+        self.assert_compatible(other);
+        let mut inner = self.inner.clone();
+        inner.or(&other.inner);
+        BloomFilter {
+            inner,
+            n_bits: self.n_bits,
+            k: self.k,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Combine this filter with `other`, returning a new filter whose set
+    /// bits are the intersection of both. This approximates the
+    /// intersection of the two underlying sets: a key reported as present
+    /// in the result was (probably) present in both filters.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `self` and `other` don't share the same `n_bits` and `k`.
+    pub fn intersect(&self, other: &BloomFilter<K>) -> BloomFilter<K> {
+        self.assert_compatible(other);
+        let mut inner = self.inner.clone();
+        inner.and(&other.inner);
+        BloomFilter {
+            inner,
+            n_bits: self.n_bits,
+            k: self.k,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Panic with a clear message unless `self` and `other` were built with
+    /// the same `n_bits` and `k`, which [`union`](Self::union) and
+    /// [`intersect`](Self::intersect) require in order for bit positions to
+    /// be comparable between filters.
+    fn assert_compatible(&self, other: &BloomFilter<K>) {
+        assert_eq!(
+            self.n_bits, other.n_bits,
+            "cannot combine filters with different n_bits ({} vs {})",
+            self.n_bits, other.n_bits
+        );
+        assert_eq!(
+            self.k, other.k,
+            "cannot combine filters with different k ({} vs {})",
+            self.k, other.k
+        );
+    }
+
+    /// Serialize the filter into a compact byte buffer, suitable for
+    /// writing to disk or sending to another node.
+    ///
+    /// The layout is `n_bits` (u64 LE) followed by `k` (u64 LE), then the
+    /// packed bit vector's length in bytes (u64 LE) and finally the packed
+    /// bytes themselves, as produced by [`BitVec::to_bytes`]. Reconstruct a
+    /// filter from this buffer with [`BloomFilter::from_bytes`].
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let packed = self.inner.to_bytes();
+        let mut out = Vec::with_capacity(24 + packed.len());
+        out.extend_from_slice(&(self.n_bits as u64).to_le_bytes());
+        out.extend_from_slice(&(self.k as u64).to_le_bytes());
+        out.extend_from_slice(&(packed.len() as u64).to_le_bytes());
+        out.extend_from_slice(&packed);
+        out
+    }
+
+    /// Reconstruct a filter previously serialized with
+    /// [`BloomFilter::to_bytes`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bytes` is too short to contain a valid header and packed
+    /// bit vector, or if the declared `n_bits` exceeds what the packed bit
+    /// vector actually holds. These checks matter because this buffer is
+    /// expected to come from disk or another node and so can't be trusted
+    /// to be well-formed.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        assert!(
+            bytes.len() >= 24,
+            "buffer too short to contain a serialized BloomFilter header"
+        );
+
+        let n_bits = u64::from_le_bytes(bytes[0..8].try_into().unwrap()) as usize;
+        let k = u64::from_le_bytes(bytes[8..16].try_into().unwrap()) as usize;
+        let packed_len = u64::from_le_bytes(bytes[16..24].try_into().unwrap()) as usize;
+        let end = 24usize
+            .checked_add(packed_len)
+            .filter(|&end| bytes.len() >= end)
+            .expect("buffer too short to contain the packed bit vector");
+
+        let packed = &bytes[24..end];
+        let mut inner = BitVec::from_bytes(packed);
+        assert!(
+            inner.len() >= n_bits,
+            "packed bit vector ({} bits) is too short for the declared n_bits ({n_bits})",
+            inner.len()
+        );
+        inner.truncate(n_bits);
+
+        Self {
+            inner,
+            n_bits,
+            k,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// The fraction of bits in the filter which are currently set, in the
+    /// range `0.0..=1.0`.
+    pub fn fill_ratio(&self) -> f64 {
+        self.set_bits() as f64 / self.n_bits as f64
+    }
+
+    /// Approximate the number of items inserted into the filter so far,
+    /// based on how many bits are set, via `n = -(m/k) * ln(1 - X/m)` where
+    /// `X` is the number of set bits and `m` is `n_bits`.
+    ///
+    /// Useful for deciding when a filter has become saturated and should be
+    /// rebuilt or resized, without needing to track insertions externally.
+    pub fn estimated_items(&self) -> f64 {
+        let m = self.n_bits as f64;
+        let x = self.set_bits() as f64;
+        -(m / self.k as f64) * (1.0 - x / m).ln()
+    }
+
+    /// Approximate the filter's current false-positive probability, based on
+    /// its estimated item count, via `(1 - e^(-k*n/m))^k`.
+    pub fn estimated_fp_rate(&self) -> f64 {
+        let m = self.n_bits as f64;
+        let k = self.k as f64;
+        let n = self.estimated_items();
+        (1.0 - (-k * n / m).exp()).powf(k)
+    }
+
+    /// Count of bits currently set within the internal bit vector.
+    fn set_bits(&self) -> usize {
+        self.inner.iter().filter(|bit| *bit).count()
+    }
+}
+
+/// A counting variant of [`BloomFilter`] which supports removal.
+///
+/// A plain [`BloomFilter`] can never have items removed from it, since
+/// clearing a bit could belong to other items which hash to the same
+/// position, silently introducing false negatives. A [`CountingBloomFilter`]
+/// instead keeps a small counter per slot rather than a single bit: `insert`
+/// increments the `k` counters for a key and [`CountingBloomFilter::remove`]
+/// decrements them, so a slot only goes back to zero once nothing hashing to
+/// it remains.
+///
+/// # Tradeoffs
+///
+/// - Memory cost is `n_bits` bytes rather than `n_bits` bits, i.e. 8x larger
+///   than the equivalent [`BloomFilter`].
+/// - Counters saturate at [`u8::MAX`] rather than overflowing, so a slot
+///   incremented more than [`u8::MAX`] times loses count: it reaches zero
+///   after [`u8::MAX`] removals even while other inserted keys still hash to
+///   it, reintroducing false negatives in pathological, extremely hot-slot
+///   cases.
+pub struct CountingBloomFilter<K: Hash> {
+    /// Per-slot counters, one per bit position in the equivalent
+    /// [`BloomFilter`].
+    inner: Vec<u8>,
+    /// Number of counters the filter was initialised with.
+    n_bits: usize,
+    /// Number of times to run the hash.
+    k: usize,
+    _phantom: PhantomData<K>,
+}
+
+impl<K: Hash> CountingBloomFilter<K> {
+    /// Create a new [`CountingBloomFilter`].
+    pub fn new(n_bits: usize, k: usize) -> Self {
+        Self {
+            inner: vec![0; n_bits],
+            n_bits,
+            k,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Insert a value into the bloom filter, incrementing each of the `k`
+    /// counters it hashes to.
+    pub fn insert(&mut self, key: K) {
+        for index in hash_indices(&key, self.n_bits, self.k) {
+            self.inner[index] = self.inner[index].saturating_add(1);
+        }
+    }
+
+    /// Remove a value from the bloom filter, decrementing each of the `k`
+    /// counters it hashes to.
+    ///
+    /// Only call this for a key that was actually inserted; removing a key
+    /// that was never inserted decrements counters that other keys may
+    /// depend on.
+    pub fn remove(&mut self, key: K) {
+        for index in hash_indices(&key, self.n_bits, self.k) {
+            self.inner[index] = self.inner[index].saturating_sub(1);
+        }
+    }
+
+    /// Determine whether a value is contained within the bloom filter.
+    ///
+    /// As with [`BloomFilter::check`], this can return false positives but
+    /// not false negatives (outside of the counter-saturation edge case
+    /// documented on [`CountingBloomFilter`] itself).
+    pub fn check(&mut self, key: K) -> bool {
+        hash_indices(&key, self.n_bits, self.k)
+            .into_iter()
+            .all(|index| self.inner[index] != 0)
+    }
 }
 
 #[cfg(test)]
 mod test {
     use bit_vec::BitVec;
-    use fxhash::FxHasher;
     use rand::Rng;
 
-    use super::BloomFilter;
+    use super::{BloomFilter, CountingBloomFilter};
 
     /// Assertion over a provided [`BitVec`] and one which is constructed based
     /// on provided integer literals to the [`create_bit_vec`] macro.
@@ -118,6 +390,25 @@ mod test {
         assert_eq!(bloom.k, 2);
     }
 
+    #[test]
+    fn with_rate() {
+        let bloom: BloomFilter<&str> = BloomFilter::with_rate(1_000_000, 0.01);
+        assert_eq!(bloom.n_bits, 9_585_059);
+        assert_eq!(bloom.k, 7);
+    }
+
+    #[test]
+    #[should_panic(expected = "fp_rate must be within (0, 1)")]
+    fn with_rate_rejects_invalid_fp_rate() {
+        let _: BloomFilter<&str> = BloomFilter::with_rate(100, 1.5);
+    }
+
+    #[test]
+    #[should_panic(expected = "expected_items must be greater than 0")]
+    fn with_rate_rejects_zero_expected_items() {
+        let _: BloomFilter<&str> = BloomFilter::with_rate(0, 0.01);
+    }
+
     macro_rules! bloom_filter_types {
         ($type:ty, $n:literal, $k:literal, $generator:expr) => {
             paste::paste! {
@@ -164,7 +455,7 @@ mod test {
         bloom.insert("hello");
         assert_bit_vec!(bloom.inner, 0, 6);
         bloom.insert("world");
-        assert_bit_vec!(bloom.inner, 0, 2, 4, 6);
+        assert_bit_vec!(bloom.inner, 0, 4, 6);
 
         let mut bloom: BloomFilter<i32> = BloomFilter::new(1000, 4);
 
@@ -176,8 +467,9 @@ mod test {
 
     #[test]
     fn index() {
-        let mut bloom: BloomFilter<&str> = BloomFilter::new(10, 2);
-        assert_eq!(bloom.hash_index(&"hello", &mut FxHasher::default()), 0);
+        let indices = super::hash_indices(&"hello", 10, 2);
+        assert_eq!(indices.len(), 2);
+        assert!(indices.iter().all(|i| *i < 10));
     }
 
     #[test]
@@ -189,4 +481,145 @@ mod test {
             assert!(!bloom.check(format!("{i}")));
         }
     }
+
+    #[test]
+    fn counting_insert_and_check() {
+        let mut bloom: CountingBloomFilter<&str> = CountingBloomFilter::new(100, 4);
+        bloom.insert("hello");
+        assert!(bloom.check("hello"));
+        assert!(!bloom.check("world"));
+    }
+
+    #[test]
+    fn counting_remove() {
+        let mut bloom: CountingBloomFilter<&str> = CountingBloomFilter::new(100, 4);
+        bloom.insert("hello");
+        assert!(bloom.check("hello"));
+
+        bloom.remove("hello");
+        assert!(!bloom.check("hello"));
+    }
+
+    #[test]
+    fn counting_remove_keeps_shared_slots_alive() {
+        let mut bloom: CountingBloomFilter<i32> = CountingBloomFilter::new(1000, 4);
+        for i in 0..100 {
+            bloom.insert(i);
+        }
+
+        bloom.remove(0);
+        for i in 1..100 {
+            assert!(bloom.check(i));
+        }
+    }
+
+    #[test]
+    fn union() {
+        let mut a: BloomFilter<&str> = BloomFilter::new(100, 4);
+        a.insert("hello");
+        let mut b: BloomFilter<&str> = BloomFilter::new(100, 4);
+        b.insert("world");
+
+        let mut combined = a.union(&b);
+        assert!(combined.check("hello"));
+        assert!(combined.check("world"));
+    }
+
+    #[test]
+    fn intersect() {
+        let mut a: BloomFilter<&str> = BloomFilter::new(100, 4);
+        a.insert("hello");
+        a.insert("world");
+        let mut b: BloomFilter<&str> = BloomFilter::new(100, 4);
+        b.insert("world");
+
+        let mut combined = a.intersect(&b);
+        assert!(combined.check("world"));
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot combine filters with different n_bits")]
+    fn union_rejects_mismatched_n_bits() {
+        let a: BloomFilter<&str> = BloomFilter::new(100, 4);
+        let b: BloomFilter<&str> = BloomFilter::new(200, 4);
+        a.union(&b);
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot combine filters with different k")]
+    fn union_rejects_mismatched_k() {
+        let a: BloomFilter<&str> = BloomFilter::new(100, 4);
+        let b: BloomFilter<&str> = BloomFilter::new(100, 3);
+        a.union(&b);
+    }
+
+    #[test]
+    fn round_trip_bytes() {
+        let mut bloom: BloomFilter<&str> = BloomFilter::new(100, 4);
+        bloom.insert("hello");
+        bloom.insert("world");
+
+        let bytes = bloom.to_bytes();
+        let mut restored: BloomFilter<&str> = BloomFilter::from_bytes(&bytes);
+
+        assert_eq!(restored.n_bits, bloom.n_bits);
+        assert_eq!(restored.k, bloom.k);
+        assert!(restored.check("hello"));
+        assert!(restored.check("world"));
+        assert!(!restored.check("missing"));
+    }
+
+    #[test]
+    #[should_panic(expected = "buffer too short to contain the packed bit vector")]
+    fn from_bytes_rejects_truncated_packed_data() {
+        let mut bloom: BloomFilter<&str> = BloomFilter::new(100, 4);
+        bloom.insert("hello");
+
+        let mut bytes = bloom.to_bytes();
+        bytes.truncate(bytes.len() - 5);
+
+        let _: BloomFilter<&str> = BloomFilter::from_bytes(&bytes);
+    }
+
+    #[test]
+    #[should_panic(expected = "packed bit vector (8 bits) is too short for the declared n_bits (1000000)")]
+    fn from_bytes_rejects_n_bits_exceeding_packed_data() {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&1_000_000u64.to_le_bytes());
+        bytes.extend_from_slice(&4u64.to_le_bytes());
+        bytes.extend_from_slice(&1u64.to_le_bytes());
+        bytes.push(0);
+
+        let _: BloomFilter<&str> = BloomFilter::from_bytes(&bytes);
+    }
+
+    #[test]
+    fn fill_ratio() {
+        let mut bloom: BloomFilter<&str> = BloomFilter::new(10, 2);
+        assert_eq!(bloom.fill_ratio(), 0.0);
+
+        bloom.insert("hello");
+        assert_eq!(bloom.fill_ratio(), 0.2);
+    }
+
+    #[test]
+    fn estimated_items_and_fp_rate_track_insertions() {
+        let mut bloom: BloomFilter<i32> = BloomFilter::with_rate(1_000, 0.01);
+
+        for i in 0..500 {
+            bloom.insert(i);
+        }
+
+        let estimate = bloom.estimated_items();
+        assert!(
+            (400.0..=600.0).contains(&estimate),
+            "expected ~500 items, got {estimate}"
+        );
+
+        let fp_rate = bloom.estimated_fp_rate();
+        assert!(
+            (0.0..=1.0).contains(&fp_rate),
+            "fp rate out of range: {fp_rate}"
+        );
+    }
 }